@@ -1,8 +1,6 @@
-mod hfps;
-mod loader;
-
 use macroquad::prelude::*;
-use hfps::HfpsAgent;
+use hfps::hfps::HfpsAgent;
+use hfps::loader;
 
 #[macroquad::main("HFPS Project: Neuro-Homeostatic Core")]
 async fn main() {
@@ -103,10 +101,8 @@ async fn main() {
         }
 
         // Decay (Homeostasis)
-        // In the real engine, this is handled by System::tick()
-        for i in 0..4 {
-            npc.channels[i] *= 0.998; // Slow recovery to zero
-        }
+        // Same decay models as AgentSystem::tick(), so tuning here matches the real engine.
+        npc.decay(get_frame_time());
 
         next_frame().await
     }
@@ -1,4 +1,9 @@
-use crate::hfps::{AgentSystem, calculate_hash};
+//! Event propagation: `WorldEvent` pulses and the `EventBroker` that applies them.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use crate::hfps::{AgentHandle, AgentSystem, BehaviorProfile, SpeciesId, calculate_hash};
+use crate::registry::{EventId, EventRegistry};
 use crate::spatial::SpatialGrid;
 
 /// Represents a physical event occurring in the game world.
@@ -14,25 +19,174 @@ pub struct WorldEvent {
     /// The raw strength of the event at the source.
     /// This value is attenuated by distance before reaching the agent.
     pub base_intensity: f32,
+
+    /// Optional reach of the event at the source (e.g., an `Emission` radius).
+    /// Listeners never hear it beyond this distance, even if their own radius is larger.
+    /// `None` leaves the listener's radius as the only limit.
+    /// A zero or negative reach is heard by nobody.
+    pub radius: Option<f32>,
+
+    /// The agent that emitted this event, if any.
+    /// Environmental events (fire, explosions) have no source.
+    pub source: Option<EventSource>,
+}
+
+/// Identifies the agent behind an emitted `WorldEvent`.
+#[derive(Debug, Clone)]
+pub struct EventSource {
+    /// Handle of the emitting agent.
+    /// The broker never applies an event to its own emitter (no self-feedback).
+    pub agent: AgentHandle,
+    /// Species of the emitting agent.
+    pub species: Arc<BehaviorProfile>,
 }
 
-/// A static service responsible for propagating events to agents.
+impl WorldEvent {
+    /// Creates an environmental event with no source and no reach limit of its own.
+    pub fn new(name: impl Into<String>, origin: (f32, f32), base_intensity: f32) -> Self {
+        Self { name: name.into(), origin, base_intensity, radius: None, source: None }
+    }
+
+    /// Tags the event with the agent (and species) that emitted it.
+    pub fn with_source(mut self, agent: AgentHandle, species: Arc<BehaviorProfile>) -> Self {
+        self.source = Some(EventSource { agent, species });
+        self
+    }
+}
+
+/// The service responsible for propagating events to agents.
 /// It uses the SpatialGrid to find affected agents in O(1) time.
-pub struct EventBroker;
+///
+/// The broker owns an `EventRegistry` that knows, per event type, which species
+/// listen to it and the largest listener radius. That radius bounds the broad phase,
+/// so the profile radii are authoritative, and agents of uninterested species are
+/// skipped without touching their profile.
+#[derive(Debug, Default)]
+pub struct EventBroker {
+    pub registry: EventRegistry,
+    /// Hashes of events already reported as unheard, so each is only reported once.
+    unheard: HashSet<u64>,
+    /// Scratch buffer for `emit`, reused between calls.
+    hits: Vec<Hit>,
+}
+
+/// An event resolved against the registry, ready to be gathered.
+#[derive(Debug, Clone, Copy)]
+struct EventTarget {
+    id: EventId,
+    /// Current index of the emitting agent, if it is still alive.
+    emitter: Option<usize>,
+}
+
+/// One listener reached by an event: the raw (attenuated) pressure, before the
+/// agent's sensitivity, adaptation and DNA are applied.
+#[derive(Debug, Clone, Copy)]
+struct Hit {
+    agent: u32,
+    species: SpeciesId,
+    /// Index of the matching `Stimulus` in the species' `listeners`.
+    listener: u32,
+    channel: u8,
+    pressure: f32,
+}
 
 impl EventBroker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Indexes the listeners of a species profile.
+    /// Must be called for every profile before its agents can hear events
+    /// (`World::add_agent` does this automatically).
+    pub fn register_profile(&mut self, profile: &Arc<BehaviorProfile>) {
+        self.registry.register_profile(profile);
+    }
+
     /// Emits an event into the world, updating all affected agents.
+    /// Events nobody listens to are dropped, with a one-time warning on stderr.
     /// 
     /// # Arguments
     /// * `event` - The event to be propagated.
     /// * `system` - The mutable agent system (where agent channels are stored).
     /// * `grid` - The spatial grid for spatial partitioning lookups.
-    pub fn emit(event: &WorldEvent, system: &mut AgentSystem, grid: &SpatialGrid) {
+    pub fn emit(&mut self, event: &WorldEvent, system: &mut AgentSystem, grid: &SpatialGrid) {
+        let Some(target) = self.prepare(event, system) else { return };
+
+        let mut hits = std::mem::take(&mut self.hits);
+        Self::gather(&self.registry, event, target, system, grid, &mut hits);
+        Self::apply(&hits, system);
+        hits.clear();
+        self.hits = hits;
+    }
+
+    /// Emits a batch of events, in order.
+    ///
+    /// Produces exactly the same result as calling `emit` for each event in turn.
+    /// With the `parallel` feature, the spatial search (the expensive part) runs
+    /// for all events concurrently; the pressure is then applied in event order,
+    /// since each application depends on the adaptation left by the previous one.
+    pub fn emit_batch(&mut self, events: &[WorldEvent], system: &mut AgentSystem, grid: &SpatialGrid) {
+        #[cfg(not(feature = "parallel"))]
+        for event in events {
+            self.emit(event, system, grid);
+        }
+
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::prelude::*;
+
+            let targets: Vec<Option<EventTarget>> = events.iter().map(|e| self.prepare(e, system)).collect();
+            let registry = &self.registry;
+            let shared: &AgentSystem = system;
+            let hits: Vec<Vec<Hit>> = events
+                .par_iter()
+                .zip(targets.par_iter())
+                .map(|(event, target)| {
+                    let mut out = Vec::new();
+                    if let Some(target) = target {
+                        Self::gather(registry, event, *target, shared, grid, &mut out);
+                    }
+                    out
+                })
+                .collect();
+            for event_hits in &hits {
+                Self::apply(event_hits, system);
+            }
+        }
+    }
+
+    /// Resolves the event against the registry. Returns `None` (and warns once)
+    /// if no registered species listens to it.
+    fn prepare(&mut self, event: &WorldEvent, system: &AgentSystem) -> Option<EventTarget> {
         let event_hash = calculate_hash(&event.name);
-        
+        match self.registry.id_by_hash(event_hash) {
+            Some(id) if !self.registry.entry(id).listeners.is_empty() => Some(EventTarget {
+                id,
+                // Resolved once: stale handles (emitter despawned since) simply match nobody.
+                emitter: event.source.as_ref().and_then(|s| system.resolve(s.agent)),
+            }),
+            _ => {
+                if self.unheard.insert(event_hash) {
+                    eprintln!("[hfps] warning: event '{}' was emitted but no registered species listens to it", event.name);
+                }
+                None
+            }
+        }
+    }
+
+    /// Finds every agent reached by the event and the raw pressure it receives.
+    /// Read-only, so it can run for many events at once.
+    fn gather(registry: &EventRegistry, event: &WorldEvent, target: EventTarget, system: &AgentSystem, grid: &SpatialGrid, out: &mut Vec<Hit>) {
+        let entry = registry.entry(target.id);
+
         // Find cells within the maximum possible influence radius.
-        // Hardcoded to 500.0 for this demo, but should be dynamic based on event intensity.
-        let nearby_cell_indices = grid.get_nearby_cells(event.origin, 500.0); 
+        // Events with their own (smaller) reach only need to scan that far.
+        let query_radius = event.radius.map_or(entry.max_radius, |r| r.min(entry.max_radius));
+        // A zero reach hears nothing (and would divide by zero in the falloff below).
+        if query_radius <= 0.0 {
+            return;
+        }
+        let nearby_cell_indices = grid.get_nearby_cells(event.origin, query_radius);
 
         // Iterate over potentially affected cells (broad phase)
         for cell_idx in nearby_cell_indices {
@@ -41,51 +195,163 @@ impl EventBroker {
             // Traverse the linked list of agents in this cell (narrow phase)
             while agent_idx != -1 {
                 let i = agent_idx as usize;
+                agent_idx = grid.next[i];
                 
+                // Self-Exclusion: An agent never hears its own emission.
+                if target.emitter == Some(i) {
+                    continue;
+                }
+
+                // Check if the agent's species actually cares about this event type.
+                // Only the Arc pointer is compared; the profile itself is not touched.
+                let sid = system.species_id[i];
+                let Some(interest) = entry.listeners.iter().find(|l| Arc::ptr_eq(&l.species, &system.species.profiles[sid as usize])) else {
+                    continue;
+                };
+
                 // Calculate squared distance to avoid expensive sqrt() unless necessary
                 let dx = system.x[i] - event.origin.0;
                 let dy = system.y[i] - event.origin.1;
                 let dist_sq = dx*dx + dy*dy;
 
-                let profile = &system.profiles[i];
-                
-                // Check if the agent actually cares about this event type
-                for stimulus in profile.listeners.iter() {
-                    if stimulus.event_hash == event_hash {
-                        let radius_sq = stimulus.radius * stimulus.radius;
-                        if dist_sq <= radius_sq {
-                            let distance = dist_sq.sqrt();
-                            
-                            // Linear attenuation: Pressure drops to 0 at max radius.
-                            let falloff = 1.0 - (distance / stimulus.radius);
-                            let final_pressure = event.base_intensity * stimulus.power * falloff;
-                            
-                            let chan_idx = stimulus.channel as usize;
-                            
-                            // --- APPLY PRESSURE (SoA) ---
-                            // 1. Base Sensitivity (from DNA Profile)
-                            // 2. Adaptation Modifiers (Habituation - Short Term)
-                            // 3. DNA Modifiers (Epigenetics - Long Term)
-                            let effective_sens = profile.sensitivity[chan_idx] 
-                                * (1.0 + system.adaptation[chan_idx][i]) 
-                                * system.dna_modifiers[chan_idx][i];
-                                
-                            system.channels[chan_idx][i] += final_pressure * effective_sens;
-                            
-                            // --- UPDATE ADAPTATION (Neuroplasticity) ---
-                            // Applying pressure also "hardens" the agent against future pressure on this channel.
-                            // This creates the "Callus Effect".
-                            let adaptation_push = final_pressure.abs() * profile.adaptation_rates[chan_idx] * 0.01;
-                            
-                            // Clamp adaptation between -0.9 (Hypersensitive) and 2.0 (Numb).
-                            system.adaptation[chan_idx][i] = (system.adaptation[chan_idx][i] - adaptation_push).clamp(-0.9, 2.0);
-                        }
+                for &k in interest.stimuli.iter() {
+                    let stimulus = &interest.species.listeners[k];
+                    // The event is heard up to the smaller of the two ranges.
+                    let radius = event.radius.map_or(stimulus.radius, |r| r.min(stimulus.radius));
+                    let radius_sq = radius * radius;
+                    if radius > 0.0 && dist_sq <= radius_sq {
+                        let distance = dist_sq.sqrt();
+                        
+                        // Attenuation: The listener's curve decides how pressure fades up to max radius.
+                        let falloff = stimulus.falloff.attenuate(distance / radius);
+                        out.push(Hit {
+                            agent: i as u32,
+                            species: sid,
+                            listener: k as u32,
+                            channel: stimulus.channel as u8,
+                            pressure: event.base_intensity * stimulus.power * falloff,
+                        });
                     }
                 }
+            }
+        }
+    }
+
+    /// Applies gathered hits to the agents, in order.
+    fn apply(hits: &[Hit], system: &mut AgentSystem) {
+        for hit in hits {
+            let i = hit.agent as usize;
+            let sid = hit.species as usize;
+            let chan_idx = hit.channel as usize;
+            let listener = hit.listener as usize;
+            let final_pressure = hit.pressure;
+
+            // --- APPLY PRESSURE (SoA) ---
+            // 1. Base Sensitivity (from DNA Profile)
+            // 2. Adaptation Modifiers (Habituation to this stimulus - Short Term)
+            // 3. DNA Modifiers (Epigenetics - Long Term)
+            let effective_sens = system.species.sensitivity[chan_idx][sid]
+                * (1.0 + system.stimulus_adaptation[listener][i]) 
+                * system.dna_modifiers[chan_idx][i];
                 
-                // Move to the next agent in the linked list
-                agent_idx = grid.next[agent_idx as usize];
+            system.channels[chan_idx][i] += final_pressure * effective_sens;
+            
+            // --- UPDATE ADAPTATION (Neuroplasticity) ---
+            // Applying pressure also "hardens" the agent against future pressure on this channel
+            // (the "Callus Effect"), or, for sensitizing channels, primes it (trauma).
+            let plasticity = &system.species.plasticity[chan_idx][sid];
+            let adaptation_push = final_pressure.abs() * system.species.adaptation_rates[chan_idx][sid] * plasticity.adaptation_gain
+                * plasticity.direction.sign(final_pressure);
+            
+            // Only this stimulus is affected; a novel event on the same channel still hits at full strength.
+            // The channel-wide buffer accumulates too, as it drives the long-term DNA drift.
+            // Clamp adaptation between `adaptation_min` (Numb) and `adaptation_max` (Hypersensitive).
+            let (min, max) = (plasticity.adaptation_min, plasticity.adaptation_max);
+            system.stimulus_adaptation[listener][i] = (system.stimulus_adaptation[listener][i] + adaptation_push).clamp(min, max);
+            system.adaptation[chan_idx][i] = (system.adaptation[chan_idx][i] + adaptation_push).clamp(min, max);
+        }
+    }
+
+    /// --- EMISSION PHASE ---
+    /// Turns the `Emission` rules of every agent into events.
+    /// For each agent whose `active_flags` contain an emission's flag, a `WorldEvent` is pushed
+    /// to `out` at the agent's position, with the emission's power as intensity and its radius as reach.
+    ///
+    /// `out` is not cleared, so callers can reuse a buffer across frames.
+    pub fn collect_emissions(system: &AgentSystem, out: &mut Vec<WorldEvent>) {
+        for i in 0..system.count {
+            let flags = system.active_flags[i];
+            let profile = system.profile(i);
+            for emission in profile.emissions.iter() {
+                if flags & emission.flag != 0 {
+                    out.push(WorldEvent {
+                        name: emission.event_name.clone(),
+                        origin: (system.x[i], system.y[i]),
+                        base_intensity: emission.power,
+                        radius: Some(emission.radius),
+                        source: Some(EventSource { agent: system.handle(i), species: profile.clone() }),
+                    });
+                }
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hfps::Channel;
+    use crate::loader::load_profile;
+
+    #[test]
+    fn zero_or_negative_reach_is_heard_by_nobody() {
+        let sheep = load_profile("data/ovelha.toml");
+        let mut broker = EventBroker::new();
+        broker.register_profile(&sheep);
+        let mut system = AgentSystem::new(1);
+        let mut grid = SpatialGrid::new(1000.0, 1000.0, 100.0, 1);
+        system.add_agent((500.0, 500.0), sheep);
+        grid.insert(0, (500.0, 500.0));
+        let before = system.channels[Channel::Security as usize][0];
+
+        for radius in [0.0, -200.0] {
+            let mut event = WorldEvent::new("fogo_proximo", (500.0, 500.0), 10.0);
+            event.radius = Some(radius);
+            broker.emit(&event, &mut system, &grid);
+        }
+        assert_eq!(system.channels[Channel::Security as usize][0], before);
+    }
+
+    #[test]
+    fn habituation_is_specific_to_the_repeated_stimulus() {
+        let sheep = load_profile("data/ovelha.toml");
+        let mut broker = EventBroker::new();
+        broker.register_profile(&sheep);
+        let mut system = AgentSystem::new(1);
+        let mut grid = SpatialGrid::new(1000.0, 1000.0, 100.0, 1);
+        system.add_agent((500.0, 500.0), sheep.clone());
+        grid.insert(0, (500.0, 500.0));
+
+        let security = Channel::Security as usize;
+        let mut hit = |name: &str, system: &mut AgentSystem| {
+            let before = system.channels[security][0];
+            broker.emit(&WorldEvent::new(name, (500.0, 500.0), 1.0), system, &grid);
+            system.channels[security][0] - before
+        };
+        let full_strength = |name: &str| {
+            let stimulus = sheep.listeners.iter().find(|l| l.event_name == name).unwrap();
+            stimulus.power * sheep.sensitivity[security]
+        };
+
+        let first_fire = hit("fogo_proximo", &mut system);
+        for _ in 0..50 {
+            hit("fogo_proximo", &mut system);
+        }
+        let last_fire = hit("fogo_proximo", &mut system);
+        let first_wolf = hit("lobo_presente", &mut system);
+
+        assert!((first_fire - full_strength("fogo_proximo")).abs() < 1e-3);
+        assert!(last_fire.abs() < first_fire.abs() * 0.75, "the repeated fire should be attenuated ({} vs {})", last_fire, first_fire);
+        assert!((first_wolf - full_strength("lobo_presente")).abs() < 1e-3, "a novel wolf should land at full strength ({})", first_wolf);
+    }
+}
@@ -0,0 +1,32 @@
+//! # HFPS: Homeostatic Fluidic Pulse System
+//!
+//! A data-oriented AI engine where agents are modelled as physiological entities.
+//! Events in the world are "pulses" that apply pressure to each agent's internal
+//! channels; homeostasis (decay) and neuroplasticity (adaptation) shape the response.
+//!
+//! The crate is organised in six modules:
+//! - [`hfps`]: Core data model (`Channel`, `BehaviorProfile`) and the SoA `AgentSystem`.
+//! - [`spatial`]: The zero-allocation `SpatialGrid` used for neighbour lookups.
+//! - [`broker`]: `WorldEvent` and the `EventBroker` that propagates them to agents.
+//! - [`registry`]: The `EventRegistry` (event names and their listeners) and the `FlagRegistry` (flag names).
+//! - [`loader`]: TOML loading of species profiles from `data/`.
+//! - [`world`]: The `World` orchestrator that steps all of the above in a fixed order.
+//!
+//! The macroquad dashboard lives in the `hfps-visualizer` binary and is only built
+//! with the `visualizer` feature, so the core has no windowing dependencies.
+
+pub mod broker;
+pub mod hfps;
+pub mod loader;
+pub mod registry;
+pub mod spatial;
+pub mod world;
+
+pub use broker::{EventBroker, EventSource, WorldEvent};
+pub use hfps::{
+    AgentHandle, AgentSystem, BehaviorProfile, Channel, ChannelTest, Condition, DecayModel, DnaKernel, FixedTimestep, Flag, FlagTransition, FLAG_IDLE,
+    Plasticity, PlasticityDirection, SpeciesId, SpeciesTable, StateRule, TransitionKind,
+};
+pub use registry::{EventId, EventRegistry, FlagRegistry};
+pub use spatial::SpatialGrid;
+pub use world::World;
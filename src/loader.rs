@@ -1,19 +1,28 @@
+//! Loading of species `BehaviorProfile`s from the TOML files in `data/`,
+//! and saving / loading of per-agent `DnaKernel`s (`.dna` files, e.g. `data/mutants/`).
+
 use std::fs;
+use std::io;
 use std::collections::HashMap;
 use std::sync::Arc;
-use crate::hfps::{BehaviorProfile, Threshold, Stimulus, Emission};
+use crate::hfps::{BehaviorProfile, FLAG_IDLE, Channel, Condition, DecayModel, DnaKernel, Threshold, Stimulus, Emission, Falloff, Plasticity, PlasticityDirection, StateRule};
+use crate::registry;
 
 /// Loads a behavior profile from a TOML file.
 /// 
 /// This function handles the conversion from the human-readable TOML format
 /// (where channels are strings like "Security") to the internal indexed format
 /// (where channels are usize indices 0-3).
+/// Flag names (`flag = "fleeing"`) are resolved to bits through the process-wide
+/// `registry::flags()`, so a name means the same bit in every loaded species.
+/// Thresholds define flag names; emissions and states must use one of them (or `"idle"`).
 ///
 /// # Panics
-/// Panics if the file cannot be read or if the TOML syntax is invalid.
+/// Panics if the file cannot be read or if the TOML syntax is invalid, and on invalid
+/// values (unknown channels or flags, out-of-range parameters).
 pub fn load_profile(path: &str) -> Arc<BehaviorProfile> {
     let content = fs::read_to_string(path)
-        .expect(&format!("Error opening profile file: {}", path));
+        .unwrap_or_else(|_| panic!("Error opening profile file: {}", path));
     
     // Temporary struct to deserialize textual keys from TOML.
     #[derive(serde::Deserialize)]
@@ -21,17 +30,95 @@ pub fn load_profile(path: &str) -> Arc<BehaviorProfile> {
         name: String,
         sensitivity: HashMap<String, f32>,
         decay_rates: HashMap<String, f32>,
+        #[serde(default)]
+        decay_models: HashMap<String, DecayModel>,
         adaptation_rates: HashMap<String, f32>,
-        thresholds: Vec<Threshold>,
+        #[serde(default)]
+        baselines: HashMap<String, f32>,
+        // `[coupling.Security] Vitality = 0.05` reads "Security bleeds into Vitality".
+        #[serde(default)]
+        coupling: HashMap<String, HashMap<String, f32>>,
+        #[serde(default)]
+        plasticity: RawPlasticity,
+        thresholds: Vec<RawThreshold>,
         listeners: Vec<Stimulus>,
-        emissions: Vec<Emission>,
+        emissions: Vec<RawEmission>,
+        #[serde(default)]
+        states: Vec<RawState>,
+    }
+
+    // Same as `Threshold` / `Emission`, but with the flag still as a name.
+    // A threshold uses either the simple form (channel/value/exit) or condition/hold.
+    // Unknown keys are rejected: a typo (`exti`) would otherwise silently drop the setting.
+    #[derive(serde::Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct RawThreshold {
+        channel: Option<Channel>,
+        value: Option<f32>,
+        exit: Option<f32>,
+        condition: Option<Condition>,
+        hold: Option<Condition>,
+        flag: String,
+    }
+
+    // `[plasticity]` holds species-wide values, `[plasticity.Security]` per-channel overrides.
+    #[derive(serde::Deserialize, Default)]
+    struct RawPlasticity {
+        #[serde(flatten)]
+        species: RawPlasticityValues,
+        #[serde(flatten)]
+        channels: HashMap<String, RawPlasticityValues>,
+    }
+
+    #[derive(serde::Deserialize, Default)]
+    #[serde(deny_unknown_fields)]
+    struct RawPlasticityValues {
+        adaptation_recovery: Option<f32>,
+        adaptation_gain: Option<f32>,
+        adaptation_min: Option<f32>,
+        adaptation_max: Option<f32>,
+        direction: Option<PlasticityDirection>,
+        dna_learning_rate: Option<f32>,
+        dna_min: Option<f32>,
+        dna_max: Option<f32>,
+    }
+
+    impl RawPlasticityValues {
+        fn apply(&self, p: &mut Plasticity) {
+            if let Some(v) = self.adaptation_recovery { p.adaptation_recovery = v; }
+            if let Some(v) = self.adaptation_gain { p.adaptation_gain = v; }
+            if let Some(v) = self.adaptation_min { p.adaptation_min = v; }
+            if let Some(v) = self.adaptation_max { p.adaptation_max = v; }
+            if let Some(v) = self.direction { p.direction = v; }
+            if let Some(v) = self.dna_learning_rate { p.dna_learning_rate = v; }
+            if let Some(v) = self.dna_min { p.dna_min = v; }
+            if let Some(v) = self.dna_max { p.dna_max = v; }
+        }
+    }
+
+    #[derive(serde::Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct RawState {
+        flag: String,
+        priority: i32,
+    }
+
+    #[derive(serde::Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct RawEmission {
+        flag: String,
+        event_name: String,
+        power: f32,
+        radius: f32,
     }
 
     let raw: RawProfile = toml::from_str(&content).expect("Error parsing TOML syntax");
 
     let mut sensitivity = [1.0; 4];
     let mut decay_rates = [0.1; 4];
+    let mut decay_models = [DecayModel::Linear; 4];
     let mut adaptation_rates = [0.0; 4]; // Default: No adaptation
+    let mut baselines = [0.0; 4]; // Default: Rest at zero pressure
 
     let chan_map = [
         ("Vitality", 0), ("Security", 1), ("Dominance", 2), ("Engagement", 3)
@@ -40,26 +127,306 @@ pub fn load_profile(path: &str) -> Arc<BehaviorProfile> {
     for (name, idx) in &chan_map {
         if let Some(&val) = raw.sensitivity.get(*name) { sensitivity[*idx] = val; }
         if let Some(&val) = raw.decay_rates.get(*name) { decay_rates[*idx] = val; }
+        if let Some(&model) = raw.decay_models.get(*name) {
+            if let DecayModel::Exponential(half_life) = model && !(half_life > 0.0 && half_life.is_finite()) {
+                panic!("Exponential decay half-life for {} in {} must be positive and finite (got {})", name, path, half_life);
+            }
+            decay_models[*idx] = model;
+        }
         if let Some(&val) = raw.adaptation_rates.get(*name) { adaptation_rates[*idx] = val; }
+        if let Some(&val) = raw.baselines.get(*name) { baselines[*idx] = val; }
+    }
+
+    let chan_index = |name: &str| chan_map.iter().find(|(n, _)| *n == name).map(|(_, idx)| *idx)
+        .unwrap_or_else(|| panic!("Unknown channel '{}' in coupling of {}", name, path));
+    let mut coupling = [[0.0; 4]; 4];
+    for (from, targets) in &raw.coupling {
+        for (to, &val) in targets {
+            let (from_idx, to_idx) = (chan_index(from), chan_index(to));
+            if from_idx == to_idx {
+                panic!("Coupling {} -> {} in {} couples a channel to itself; use decay_rates instead", from, to, path);
+            }
+            coupling[from_idx][to_idx] = val;
+        }
+    }
+
+    let mut plasticity = [Plasticity::default(); 4];
+    for p in &mut plasticity {
+        raw.plasticity.species.apply(p);
+    }
+    for (name, values) in &raw.plasticity.channels {
+        let idx = chan_map.iter().find(|(n, _)| n == name).map(|(_, idx)| *idx)
+            .unwrap_or_else(|| panic!("Unknown channel or parameter '{}' in plasticity of {}", name, path));
+        values.apply(&mut plasticity[idx]);
+    }
+    for (p, (name, _)) in plasticity.iter().zip(&chan_map) {
+        if p.adaptation_min > p.adaptation_max || p.dna_min > p.dna_max {
+            panic!("Plasticity of {} in {} has a min above its max: {:?}", name, path, p);
+        }
+        if !(0.0..=1.0).contains(&p.adaptation_recovery) {
+            panic!("Plasticity adaptation_recovery of {} in {} must be within 0.0..=1.0 (got {})", name, path, p.adaptation_recovery);
+        }
+        if let PlasticityDirection::SensitizeAbove(level) = p.direction && level < 0.0 {
+            panic!("Plasticity sensitize_above level of {} in {} must not be negative (got {})", name, path, level);
+        }
     }
 
+    let (thresholds, emissions, states) = {
+        let mut flags = registry::flags();
+        let thresholds: Vec<Threshold> = raw.thresholds.into_iter()
+            .map(|t| {
+                let (condition, hold) = match (t.condition, t.channel, t.value) {
+                    (Some(condition), None, None) if t.exit.is_none() => (condition, t.hold),
+                    (None, Some(channel), Some(value)) if t.hold.is_none() => {
+                        // A release level on the wrong side of the trigger would latch the flag forever.
+                        if let Some(exit) = t.exit {
+                            let recovers = if value >= 0.0 { exit <= value } else { exit >= value };
+                            if !recovers {
+                                panic!("Threshold exit {} for {:?} in {} must lie on the recovery side of value {}", exit, channel, path, value);
+                            }
+                        }
+                        let hold = t.exit.map(|exit| Condition::level(channel, exit, value >= 0.0));
+                        (Condition::threshold(channel, value), hold)
+                    }
+                    _ => panic!("Threshold '{}' in {} needs either `channel` + `value` (+ `exit`) or `condition` (+ `hold`)", t.flag, path),
+                };
+                for c in std::iter::once(&condition).chain(hold.as_ref()) {
+                    check_condition(c).unwrap_or_else(|e| panic!("Invalid condition for '{}' in {}: {}", t.flag, path, e));
+                }
+                Threshold { condition, hold, flag: flags.intern(&t.flag) }
+            })
+            .collect();
+
+        // Only thresholds define flags. Elsewhere a name must refer to one of them (or "idle"),
+        // so a typo fails here instead of silently creating a flag that never turns on.
+        let defined = thresholds.iter().fold(FLAG_IDLE, |mask, t| mask | t.flag);
+        let lookup = |name: &str, section: &str| flags.flag(name)
+            .filter(|&flag| defined & flag != 0)
+            .unwrap_or_else(|| panic!("Unknown flag '{}' in {} of {}: flags are defined by the profile's thresholds", name, section, path));
+        let emissions = raw.emissions.into_iter()
+            .map(|e| Emission { flag: lookup(&e.flag, "emissions"), event_name: e.event_name, event_hash: 0, power: e.power, radius: e.radius })
+            .collect();
+        let mut states: Vec<StateRule> = raw.states.into_iter()
+            .map(|s| StateRule { flag: lookup(&s.flag, "states"), priority: s.priority })
+            .collect();
+        // Highest priority first, so resolving a state is a first-match scan.
+        // The sort is stable: on ties, the state declared first wins.
+        states.sort_by_key(|s| std::cmp::Reverse(s.priority));
+        (thresholds, emissions, states)
+    };
+
     let mut profile = BehaviorProfile {
         name: raw.name,
         sensitivity,
         decay_rates,
+        decay_models,
         adaptation_rates,
-        thresholds: raw.thresholds,
+        baselines,
+        coupling,
+        plasticity,
+        thresholds,
         listeners: raw.listeners,
-        emissions: raw.emissions,
+        emissions,
+        states,
     };
 
     // Pre-calculate hashes for faster runtime comparison.
     for stimulus in &mut profile.listeners {
         stimulus.event_hash = crate::hfps::calculate_hash(&stimulus.event_name);
+
+        // The falloff is a function of `distance / radius`.
+        if stimulus.radius <= 0.0 || stimulus.radius.is_nan() {
+            panic!("Listener radius for '{}' in {} must be positive (got {})", stimulus.event_name, path, stimulus.radius);
+        }
+
+        match &mut stimulus.falloff {
+            // A non-positive rate would make the pressure grow with distance.
+            Falloff::Exponential(rate) if !(*rate > 0.0 && rate.is_finite()) => {
+                panic!("Exponential falloff rate for '{}' in {} must be positive (got {})", stimulus.event_name, path, rate);
+            }
+            Falloff::Custom(points) => {
+                if points.is_empty() {
+                    panic!("Custom falloff for '{}' in {} needs at least one point", stimulus.event_name, path);
+                }
+                for &[t, value] in points.iter() {
+                    if !((0.0..=1.0).contains(&t) && value >= 0.0 && value.is_finite()) {
+                        panic!("Custom falloff point [{}, {}] for '{}' in {} needs t in 0.0..=1.0 and a non-negative value", t, value, stimulus.event_name, path);
+                    }
+                }
+                // Custom curves are evaluated by walking the points in order.
+                points.sort_by(|a, b| a[0].total_cmp(&b[0]));
+            }
+            _ => {}
+        }
     }
     for emission in &mut profile.emissions {
         emission.event_hash = crate::hfps::calculate_hash(&emission.event_name);
+
+        if emission.radius <= 0.0 || emission.radius.is_nan() {
+            panic!("Emission radius for '{}' in {} must be positive (got {})", emission.event_name, path, emission.radius);
+        }
     }
     
     Arc::new(profile)
-}
\ No newline at end of file
+}
+
+/// Reads a `DnaKernel` from a `.dna` file (JSON: `{ "modifiers": [1.0, 0.99, 1.0, 1.0] }`).
+///
+/// Unlike `load_profile`, this returns errors instead of panicking: kernels are
+/// runtime save data, and a missing or corrupt one shouldn't take a server down.
+pub fn load_dna(path: &str) -> io::Result<DnaKernel> {
+    let kernel: DnaKernel = serde_json::from_str(&fs::read_to_string(path)?)?;
+    if kernel.modifiers.iter().any(|m| !m.is_finite()) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("DNA modifiers in {} must be finite", path)));
+    }
+    Ok(kernel)
+}
+
+/// Writes a `DnaKernel` to a `.dna` file as pretty-printed JSON, replacing any existing file.
+pub fn save_dna(path: &str, kernel: &DnaKernel) -> io::Result<()> {
+    fs::write(path, serde_json::to_string_pretty(kernel)?)
+}
+
+/// Rejects conditions that can never be meaningful (empty groups, comparisons without bounds).
+fn check_condition(condition: &Condition) -> Result<(), String> {
+    match condition {
+        Condition::All { all: list } | Condition::Any { any: list } => {
+            if list.is_empty() {
+                return Err("`all` / `any` needs at least one condition".to_string());
+            }
+            list.iter().try_for_each(check_condition)
+        }
+        Condition::Not { not } => check_condition(not),
+        Condition::Compare(test) => {
+            if test.above.is_none() && test.below.is_none() {
+                return Err(format!("comparison on {:?} needs `above` and/or `below`", test.channel));
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::catch_unwind;
+
+    const BASE: &str = r#"
+name = "Test"
+sensitivity = {}
+decay_rates = {}
+adaptation_rates = {}
+"#;
+
+    /// Loads `BASE` plus `extra` from a temporary file. `Err` if the loader panicked.
+    /// Arrays `extra` doesn't declare get a default: a "fleeing" threshold, no listeners, no emissions.
+    fn load_with(name: &str, extra: &str) -> std::thread::Result<Arc<BehaviorProfile>> {
+        let mut content = BASE.to_string();
+        let defaults = [
+            ("thresholds", r#"[{ channel = "Security", value = -40.0, flag = "fleeing" }]"#),
+            ("listeners", "[]"),
+            ("emissions", "[]"),
+        ];
+        for (key, value) in defaults {
+            if !extra.contains(&format!("[[{}]]", key)) {
+                content += &format!("{} = {}\n", key, value);
+            }
+        }
+        content += extra;
+        let path = std::env::temp_dir().join(format!("hfps-loader-{}-{}.toml", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        let result = catch_unwind(|| load_profile(path.to_str().unwrap()));
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    #[test]
+    fn emissions_and_states_must_use_threshold_flags() {
+        let ok = load_with("flags-ok", r#"
+[[emissions]]
+flag = "fleeing"
+event_name = "panic"
+power = 1.0
+radius = 10.0
+
+[[states]]
+flag = "fleeing"
+priority = 1
+"#);
+        assert!(ok.is_ok());
+
+        let typo_emission = load_with("flags-emission", r#"
+[[emissions]]
+flag = "fleing"
+event_name = "panic"
+power = 1.0
+radius = 10.0
+"#);
+        assert!(typo_emission.is_err());
+
+        let typo_state = load_with("flags-state", "[[states]]\nflag = \"fleing\"\npriority = 1\n");
+        assert!(typo_state.is_err());
+        assert_eq!(registry::flags().flag("fleing"), None);
+    }
+
+    #[test]
+    fn falloff_must_not_grow_with_distance() {
+        let listener = |name: &str, falloff: &str| load_with(name, &format!(
+            "[[listeners]]\nevent_name = \"fire\"\nchannel = \"Security\"\npower = -10.0\nradius = 100.0\nfalloff = {}\n", falloff));
+        assert!(listener("falloff-ok", "{ custom = [[0.0, 1.0], [1.0, 0.0]] }").is_ok());
+        assert!(listener("falloff-exp-ok", "{ exponential = 3.0 }").is_ok());
+        assert!(listener("falloff-exp-negative", "{ exponential = -5.0 }").is_err());
+        assert!(listener("falloff-exp-nan", "{ exponential = nan }").is_err());
+        assert!(listener("falloff-custom-t", "{ custom = [[0.0, 1.0], [1.5, 0.0]] }").is_err());
+        assert!(listener("falloff-custom-value", "{ custom = [[0.0, 1.0], [1.0, -2.0]] }").is_err());
+    }
+
+    #[test]
+    fn thresholds_emissions_and_states_reject_unknown_keys() {
+        let threshold = |name: &str, key: &str| load_with(name, &format!(
+            "[[thresholds]]\nchannel = \"Security\"\nvalue = -40.0\n{} = -20.0\nflag = \"fleeing\"\n", key));
+        assert!(threshold("keys-threshold-ok", "exit").is_ok());
+        assert!(threshold("keys-threshold", "exti").is_err());
+
+        let emission = "[[emissions]]\nflag = \"fleeing\"\nevent_name = \"panic\"\npower = 1.0\nradius = 10.0\nraduis = 5.0\n";
+        assert!(load_with("keys-emission", emission).is_err());
+        assert!(load_with("keys-state", "[[states]]\nflag = \"fleeing\"\npriority = 1\nprio = 2\n").is_err());
+    }
+
+    #[test]
+    fn exponential_half_life_must_be_positive_and_finite() {
+        let decay = |name: &str, half_life: &str| load_with(name, &format!("[decay_models]\nSecurity = {{ exponential = {} }}\n", half_life));
+        assert!(decay("half-life-ok", "2.0").is_ok());
+        assert!(decay("half-life-zero", "0.0").is_err());
+        assert!(decay("half-life-nan", "nan").is_err());
+        assert!(decay("half-life-inf", "inf").is_err());
+    }
+
+    #[test]
+    fn dna_kernels_round_trip_through_files() {
+        let committed = load_dna("data/mutants/npc_0.dna").unwrap();
+        assert_eq!(committed.modifiers, [1.0, 0.9942623, 1.0, 1.0]);
+
+        let sheep = load_profile("data/ovelha.toml");
+        let mut system = crate::hfps::AgentSystem::new(2);
+        let original = system.add_agent((0.0, 0.0), sheep.clone());
+        assert!(system.inject_dna(original, &DnaKernel { modifiers: [1.5, 0.8, 1.0, 2.5] }));
+        let exported = system.export_dna(original).unwrap();
+
+        let path = std::env::temp_dir().join(format!("hfps-loader-{}-round-trip.dna", std::process::id()));
+        save_dna(path.to_str().unwrap(), &exported).unwrap();
+        let loaded = load_dna(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let clone = system.add_agent_with_dna((1.0, 1.0), sheep, &loaded);
+        assert_eq!(system.export_dna(clone).unwrap().modifiers, [1.5, 0.8, 1.0, 2.5]);
+    }
+
+    #[test]
+    fn injected_dna_is_clamped_to_the_species_range() {
+        let profile = load_with("dna-range", "[plasticity]\ndna_min = 0.5\ndna_max = 2.0\n").unwrap();
+        let mut system = crate::hfps::AgentSystem::new(1);
+        let agent = system.add_agent_with_dna((0.0, 0.0), profile, &DnaKernel { modifiers: [100.0, -3.0, 1.0, 0.7] });
+        assert_eq!(system.export_dna(agent).unwrap().modifiers, [2.0, 0.5, 1.0, 0.7]);
+    }
+}
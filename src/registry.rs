@@ -0,0 +1,185 @@
+//! Name registries: event types (with a per-event listener index) and behavior flags.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use crate::hfps::{BehaviorProfile, FLAG_IDLE, Flag, calculate_hash};
+
+/// Dense, zero-based ID assigned to an event name by the `EventRegistry`.
+/// Valid only for the registry that produced it; persist `calculate_hash` IDs instead.
+pub type EventId = u32;
+
+/// The listeners of one species for one event type.
+#[derive(Debug, Clone)]
+pub struct SpeciesListeners {
+    pub species: Arc<BehaviorProfile>,
+    /// Indices into `species.listeners` of the `Stimulus` entries matching the event.
+    pub stimuli: Vec<usize>,
+}
+
+/// Everything the broker needs to know about one event type.
+#[derive(Debug, Clone)]
+pub struct EventEntry {
+    pub name: String,
+    /// Stable hash of `name` (see `calculate_hash`).
+    pub hash: u64,
+    /// Species that react to this event. Empty if the event is only ever emitted.
+    pub listeners: Vec<SpeciesListeners>,
+    /// Largest `Stimulus::radius` across `listeners`. Bounds the broad phase.
+    pub max_radius: f32,
+}
+
+/// Interns event names and builds a per-event listener index.
+///
+/// Instead of asking every agent in range whether it cares about an event,
+/// the broker looks up the event here once and only considers agents whose
+/// species appears in `EventEntry::listeners`.
+#[derive(Debug, Default)]
+pub struct EventRegistry {
+    events: Vec<EventEntry>,
+    by_hash: HashMap<u64, EventId>,
+    /// Profiles already indexed, to make `register_profile` idempotent.
+    species: Vec<Arc<BehaviorProfile>>,
+}
+
+impl EventRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the ID of `name`, assigning the next free one if it is new.
+    pub fn intern(&mut self, name: &str) -> EventId {
+        let hash = calculate_hash(name);
+        if let Some(&id) = self.by_hash.get(&hash) {
+            return id;
+        }
+        let id = self.events.len() as EventId;
+        self.events.push(EventEntry {
+            name: name.to_string(),
+            hash,
+            listeners: Vec::new(),
+            max_radius: 0.0,
+        });
+        self.by_hash.insert(hash, id);
+        id
+    }
+
+    /// ID of an already interned event name.
+    pub fn id(&self, name: &str) -> Option<EventId> {
+        self.id_by_hash(calculate_hash(name))
+    }
+
+    /// ID of an already interned event, looked up by its stable hash.
+    pub fn id_by_hash(&self, hash: u64) -> Option<EventId> {
+        self.by_hash.get(&hash).copied()
+    }
+
+    pub fn entry(&self, id: EventId) -> &EventEntry {
+        &self.events[id as usize]
+    }
+
+    pub fn name(&self, id: EventId) -> &str {
+        &self.events[id as usize].name
+    }
+
+    /// Number of interned event types.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Interns every event a species listens to or emits, and adds the species
+    /// to the listener index. Registering the same `Arc` twice is a no-op.
+    pub fn register_profile(&mut self, profile: &Arc<BehaviorProfile>) {
+        if self.species.iter().any(|s| Arc::ptr_eq(s, profile)) {
+            return;
+        }
+        self.species.push(profile.clone());
+
+        for emission in &profile.emissions {
+            self.intern(&emission.event_name);
+        }
+
+        for (k, stimulus) in profile.listeners.iter().enumerate() {
+            let id = self.intern(&stimulus.event_name);
+            let entry = &mut self.events[id as usize];
+            entry.max_radius = entry.max_radius.max(stimulus.radius);
+
+            match entry.listeners.iter_mut().find(|l| Arc::ptr_eq(&l.species, profile)) {
+                Some(listeners) => listeners.stimuli.push(k),
+                None => entry.listeners.push(SpeciesListeners { species: profile.clone(), stimuli: vec![k] }),
+            }
+        }
+    }
+}
+
+/// Assigns flag bits to flag names ("fleeing", "aggressive", ...).
+///
+/// Profiles refer to flags by name; the loader resolves every name through the
+/// process-wide registry (see `flags()`), so the same name always maps to the
+/// same bit in every species, and two names can never share a bit.
+/// Bit 0 is reserved for `"idle"` (`FLAG_IDLE`), the default state.
+#[derive(Debug, Clone)]
+pub struct FlagRegistry {
+    /// `names[bit]` is the name of the flag `1 << bit`.
+    names: Vec<String>,
+}
+
+impl Default for FlagRegistry {
+    fn default() -> Self {
+        Self { names: vec!["idle".to_string()] }
+    }
+}
+
+impl FlagRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the flag bit for `name`, assigning the next free bit if it is new.
+    ///
+    /// # Panics
+    /// Panics if more than 32 distinct flags are declared (`Flag` is a `u32` bitmask).
+    pub fn intern(&mut self, name: &str) -> Flag {
+        if let Some(flag) = self.flag(name) {
+            return flag;
+        }
+        let bit = self.names.len();
+        assert!(bit < Flag::BITS as usize, "Too many flags: '{}' would be flag #{} but only {} fit in a Flag", name, bit + 1, Flag::BITS);
+        self.names.push(name.to_string());
+        FLAG_IDLE << bit
+    }
+
+    /// The flag bit assigned to `name`, if any.
+    pub fn flag(&self, name: &str) -> Option<Flag> {
+        self.names.iter().position(|n| n == name).map(|bit| FLAG_IDLE << bit)
+    }
+
+    /// The name of a single flag bit.
+    pub fn name(&self, flag: Flag) -> Option<&str> {
+        if !flag.is_power_of_two() {
+            return None;
+        }
+        self.names.get(flag.trailing_zeros() as usize).map(String::as_str)
+    }
+
+    /// Names of every flag set in `flags` (e.g., an agent's `active_flags`), lowest bit first.
+    /// Bits with no registered name are skipped.
+    pub fn names(&self, flags: Flag) -> Vec<&str> {
+        self.names.iter().enumerate()
+            .filter(|(bit, _)| flags & (FLAG_IDLE << bit) != 0)
+            .map(|(_, name)| name.as_str())
+            .collect()
+    }
+}
+
+/// The process-wide flag registry used by the loader.
+/// Lock it briefly (e.g., to translate `active_flags` for logging); don't hold it across a frame.
+pub fn flags() -> MutexGuard<'static, FlagRegistry> {
+    static FLAGS: OnceLock<Mutex<FlagRegistry>> = OnceLock::new();
+    FLAGS.get_or_init(|| Mutex::new(FlagRegistry::new()))
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -1,3 +1,5 @@
+//! Spatial partitioning used by the broker for broad-phase lookups.
+
 /// A high-performance static spatial partitioning system.
 /// Uses a Linked-List approach embedded in arrays to achieve O(1) insertions and lookups
 /// with ZERO heap allocations during the simulation step.
@@ -6,24 +8,52 @@
 pub struct SpatialGrid {
     /// Dimension of each square cell in world units.
     pub cell_size: f32,
+
+    /// Number of cells along the X and Y axes.
+    /// Derived from the world size: `ceil(width / cell_size)` x `ceil(height / cell_size)`.
+    pub cols: usize,
+    pub rows: usize,
     
-    /// Array of "Head" pointers for each cell.
-    /// heads[cell_index] stores the ID of the *first* agent in that cell.
+    /// Array of "Head" pointers for each cell (`cols * rows` entries).
+    /// `heads[cell_index]` stores the ID of the *first* agent in that cell.
     /// -1 indicates an empty cell.
-    pub heads: [i32; 400],    // 20x20 grid = 400 cells
+    pub heads: Vec<i32>,
     
-    /// Array of "Next" pointers for each agent.
-    /// next[agent_id] stores the ID of the *next* agent in the same cell.
+    /// Array of "Next" pointers for each agent (one entry per agent of capacity).
+    /// `next[agent_id]` stores the ID of the *next* agent in the same cell.
     /// -1 indicates the end of the list for that cell.
-    pub next: [i32; 2000],    // Capacity for 2000 agents
+    pub next: Vec<i32>,
 }
 
 impl SpatialGrid {
-    pub fn new(cell_size: f32) -> Self {
+    /// Creates a grid covering a `width` x `height` world, able to hold `capacity` agents.
+    /// Both buffers are allocated here, once. The per-frame `clear`/`insert` cycle never allocates.
+    ///
+    /// # Panics
+    /// Panics if `cell_size` is not strictly positive.
+    pub fn new(width: f32, height: f32, cell_size: f32, capacity: usize) -> Self {
+        assert!(cell_size > 0.0, "SpatialGrid cell_size must be positive (got {})", cell_size);
+        let cols = ((width / cell_size).ceil() as usize).max(1);
+        let rows = ((height / cell_size).ceil() as usize).max(1);
         Self {
             cell_size,
-            heads: [-1; 400],
-            next: [-1; 2000],
+            cols,
+            rows,
+            heads: vec![-1; cols * rows],
+            next: vec![-1; capacity],
+        }
+    }
+
+    /// Maximum number of agents the grid can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.next.len()
+    }
+
+    /// Grows the agent buffer so it can hold at least `count` agents.
+    /// This only allocates when the population outgrows the current capacity.
+    pub fn reserve_agents(&mut self, count: usize) {
+        if count > self.next.len() {
+            self.next.resize(count, -1);
         }
     }
 
@@ -34,13 +64,26 @@ impl SpatialGrid {
         for h in self.heads.iter_mut() { *h = -1; }
     }
 
+    /// Converts a world position into grid coordinates.
+    /// Positions outside the world are clamped into the border cells, so they are still found.
+    fn cell_coords(&self, pos: (f32, f32)) -> (usize, usize) {
+        let gx = ((pos.0 / self.cell_size).floor() as i64).clamp(0, self.cols as i64 - 1);
+        let gy = ((pos.1 / self.cell_size).floor() as i64).clamp(0, self.rows as i64 - 1);
+        (gx as usize, gy as usize)
+    }
+
     /// Inserts an agent into the grid.
     /// This is an O(1) operation (prepend to linked list).
+    ///
+    /// # Panics
+    /// Panics if `agent_id` is beyond the grid capacity (see `reserve_agents`).
     pub fn insert(&mut self, agent_id: usize, pos: (f32, f32)) {
-        // Calculate grid coordinates with clamping to avoid out-of-bounds panics
-        let gx = ((pos.0 / self.cell_size).floor() as i32).clamp(0, 19);
-        let gy = ((pos.1 / self.cell_size).floor() as i32).clamp(0, 19);
-        let cell_idx = (gy * 20 + gx) as usize;
+        assert!(
+            agent_id < self.next.len(),
+            "SpatialGrid capacity exceeded: agent {} but capacity is {}", agent_id, self.next.len()
+        );
+        let (gx, gy) = self.cell_coords(pos);
+        let cell_idx = gy * self.cols + gx;
 
         // Standard Linked-List Prepend:
         // 1. Point this agent's "next" to the current head of the cell.
@@ -52,21 +95,44 @@ impl SpatialGrid {
 
     /// Returns a list of cell indices that overlap with the query radius.
     /// Used for Broad Phase collision detection.
+    /// A negative (or NaN) radius is treated as 0.0: only the cell containing `pos`.
     pub fn get_nearby_cells(&self, pos: (f32, f32), radius: f32) -> Vec<usize> {
+        // A negative radius would flip the bounding box (and underflow the size below).
+        let radius = radius.max(0.0);
+
         // Determine the bounding box of the query in grid coordinates
-        let min_gx = (((pos.0 - radius) / self.cell_size).floor() as i32).clamp(0, 19);
-        let max_gx = (((pos.0 + radius) / self.cell_size).floor() as i32).clamp(0, 19);
-        let min_gy = (((pos.1 - radius) / self.cell_size).floor() as i32).clamp(0, 19);
-        let max_gy = (((pos.1 + radius) / self.cell_size).floor() as i32).clamp(0, 19);
+        let (min_gx, min_gy) = self.cell_coords((pos.0 - radius, pos.1 - radius));
+        let (max_gx, max_gy) = self.cell_coords((pos.0 + radius, pos.1 + radius));
 
         // Pre-allocate to avoid small re-allocations
-        let mut nearby = Vec::with_capacity(25);
+        let mut nearby = Vec::with_capacity((max_gx - min_gx + 1) * (max_gy - min_gy + 1));
         
         for y in min_gy..=max_gy {
             for x in min_gx..=max_gx {
-                nearby.push((y * 20 + x) as usize);
+                nearby.push(y * self.cols + x);
             }
         }
         nearby
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearby_cells_cover_the_query_box() {
+        let grid = SpatialGrid::new(1000.0, 1000.0, 100.0, 0);
+        let mut cells = grid.get_nearby_cells((550.0, 550.0), 100.0);
+        cells.sort();
+        let expected: Vec<usize> = (4..=6).flat_map(|y| (4..=6).map(move |x| y * 10 + x)).collect();
+        assert_eq!(cells, expected);
+    }
+
+    #[test]
+    fn negative_radius_yields_only_the_origin_cell() {
+        let grid = SpatialGrid::new(1000.0, 1000.0, 100.0, 0);
+        assert_eq!(grid.get_nearby_cells((500.0, 500.0), -100.0), vec![55]);
+        assert_eq!(grid.get_nearby_cells((500.0, 500.0), f32::NAN), vec![55]);
+    }
+}
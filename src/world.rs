@@ -0,0 +1,194 @@
+//! The `World` orchestrator: owns the agents, the grid and the event queue and steps them in order.
+
+use std::sync::Arc;
+use crate::broker::{EventBroker, WorldEvent};
+use crate::hfps::{AgentHandle, AgentSystem, BehaviorProfile, DnaKernel, FixedTimestep};
+use crate::spatial::SpatialGrid;
+
+/// Owns every piece of the simulation and advances it with a single call.
+///
+/// Without this, integrators have to hand-wire the grid rebuild, the broker
+/// and the tick themselves, in the right order. `World::step` does it for them:
+///
+/// 1. **Grid Rebuild:** The `SpatialGrid` is cleared and every agent is re-inserted
+///    at its current position.
+/// 2. **Event Propagation:** Queued `WorldEvent`s are drained (in push order) and
+///    routed through the `EventBroker`.
+/// 3. **Emissions:** Agents whose `active_flags` match one of their profile's `Emission`s
+///    (flags as left by the previous tick) emit events at their position, which are
+///    propagated immediately. This is how contagion (e.g., herd panic) spreads.
+/// 4. **Tick:** `AgentSystem::tick` applies homeostasis, plasticity and flag updates.
+///
+/// Positions may be changed freely between steps; the grid always reflects the
+/// positions at the start of the step.
+///
+/// Game loops with a variable frame rate should call `advance` instead, which runs
+/// `step` at the fixed rate of `clock` (60 Hz by default).
+pub struct World {
+    pub agents: AgentSystem,
+    pub grid: SpatialGrid,
+    pub broker: EventBroker,
+    /// Fixed-step accumulator used by `advance`.
+    pub clock: FixedTimestep,
+    /// Events waiting to be propagated on the next `step`.
+    /// The buffer is reused between frames to avoid allocations.
+    pending_events: Vec<WorldEvent>,
+    /// Scratch buffer for the emission phase, reused between frames.
+    emitted_events: Vec<WorldEvent>,
+}
+
+impl World {
+    /// Creates an empty world of `width` x `height` units, partitioned in cells of `cell_size`,
+    /// with room for `capacity` agents before any reallocation.
+    pub fn new(width: f32, height: f32, cell_size: f32, capacity: usize) -> Self {
+        Self {
+            agents: AgentSystem::new(capacity),
+            grid: SpatialGrid::new(width, height, cell_size, capacity),
+            broker: EventBroker::new(),
+            clock: FixedTimestep::new(1.0 / 60.0),
+            pending_events: Vec::new(),
+            emitted_events: Vec::new(),
+        }
+    }
+
+    /// Spawns an agent and registers its profile with the broker.
+    pub fn add_agent(&mut self, pos: (f32, f32), profile: Arc<BehaviorProfile>) -> AgentHandle {
+        self.broker.register_profile(&profile);
+        self.agents.add_agent(pos, profile)
+    }
+
+    /// Spawns an agent carrying learned DNA and registers its profile with the broker.
+    pub fn add_agent_with_dna(&mut self, pos: (f32, f32), profile: Arc<BehaviorProfile>, kernel: &DnaKernel) -> AgentHandle {
+        self.broker.register_profile(&profile);
+        self.agents.add_agent_with_dna(pos, profile, kernel)
+    }
+
+    /// Despawns an agent. Shortcut for `AgentSystem::remove_agent`.
+    /// Safe to call between steps: the grid is rebuilt at the start of the next one.
+    pub fn remove_agent(&mut self, handle: AgentHandle) -> bool {
+        self.agents.remove_agent(handle)
+    }
+
+    /// Queues an event to be propagated during the next `step`.
+    pub fn push_event(&mut self, event: WorldEvent) {
+        self.pending_events.push(event);
+    }
+
+    /// Events queued for the next `step`, in the order they will be processed.
+    pub fn pending_events(&self) -> &[WorldEvent] {
+        &self.pending_events
+    }
+
+    /// Advances the simulation by `dt` seconds. See the type docs for the phase order.
+    pub fn step(&mut self, dt: f32) {
+        // 1. Grid Rebuild
+        self.rebuild_grid();
+
+        // 2. Event Propagation
+        // Take the buffer so the broker can borrow the agents mutably, then hand it back
+        // (emptied, but with its capacity intact).
+        let mut events = std::mem::take(&mut self.pending_events);
+        self.broker.emit_batch(&events, &mut self.agents, &self.grid);
+        events.clear();
+        self.pending_events = events;
+
+        // 3. Emissions
+        let mut emitted = std::mem::take(&mut self.emitted_events);
+        EventBroker::collect_emissions(&self.agents, &mut emitted);
+        self.broker.emit_batch(&emitted, &mut self.agents, &self.grid);
+        emitted.clear();
+        self.emitted_events = emitted;
+
+        // 4. Tick
+        self.agents.tick(dt);
+    }
+
+    /// Advances the simulation by an arbitrary frame delta, running as many fixed
+    /// `step`s as `clock` allows. Returns the number of steps run.
+    pub fn advance(&mut self, frame_dt: f32) -> u32 {
+        let steps = self.clock.advance(frame_dt);
+        for _ in 0..steps {
+            self.step(self.clock.step);
+        }
+        steps
+    }
+
+    /// Clears the grid and re-inserts every agent at its current position.
+    pub fn rebuild_grid(&mut self) {
+        self.grid.reserve_agents(self.agents.count);
+        self.grid.clear();
+        for i in 0..self.agents.count {
+            self.grid.insert(i, (self.agents.x[i], self.agents.y[i]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::load_profile;
+
+    /// Builds a crowded world of sheep, wolves and humans at fixed pseudo-random positions.
+    /// Enough agents to span several `parallel` chunks.
+    fn populated_world() -> World {
+        let profiles = [load_profile("data/ovelha.toml"), load_profile("data/lobo.toml"), load_profile("data/humano.toml")];
+        let mut world = World::new(2000.0, 2000.0, 100.0, 2100);
+        world.agents.record_transitions = true;
+        for i in 0..2100u32 {
+            let h = i.wrapping_mul(2654435761);
+            let pos = ((h % 2000) as f32, ((h >> 11) % 2000) as f32);
+            world.add_agent(pos, profiles[i as usize % 3].clone());
+        }
+        world
+    }
+
+    fn events(frame: u32) -> Vec<WorldEvent> {
+        let x = (frame * 37 % 2000) as f32;
+        vec![
+            WorldEvent::new("fogo_proximo", (x, 1000.0), 5.0),
+            WorldEvent::new("ovelha_avistada", (1000.0, x), 1.0),
+            WorldEvent::new("humano_em_panico", (x, x), 2.0),
+        ]
+    }
+
+    /// `World::step`, but propagating every event with a separate `EventBroker::emit`.
+    fn step_one_by_one(world: &mut World, events: &[WorldEvent], dt: f32) {
+        world.rebuild_grid();
+        for event in events {
+            world.broker.emit(event, &mut world.agents, &world.grid);
+        }
+        let mut emitted = Vec::new();
+        EventBroker::collect_emissions(&world.agents, &mut emitted);
+        for event in &emitted {
+            world.broker.emit(event, &mut world.agents, &world.grid);
+        }
+        world.agents.tick(dt);
+    }
+
+    fn bits(columns: &[Vec<f32>]) -> Vec<Vec<u32>> {
+        columns.iter().map(|c| c.iter().map(|v| v.to_bits()).collect()).collect()
+    }
+
+    #[test]
+    fn batched_step_matches_per_event_emit() {
+        let (mut batched, mut reference) = (populated_world(), populated_world());
+        let dt = 1.0 / 60.0;
+        for frame in 0..60 {
+            for event in events(frame) {
+                batched.push_event(event);
+            }
+            batched.step(dt);
+            step_one_by_one(&mut reference, &events(frame), dt);
+        }
+
+        let (a, b) = (&batched.agents, &reference.agents);
+        assert!(!a.transitions().is_empty(), "the script should make agents change state");
+        assert_eq!(a.transitions(), b.transitions());
+        assert_eq!(a.active_flags, b.active_flags);
+        assert_eq!(a.behavior_state, b.behavior_state);
+        assert_eq!(bits(&a.channels), bits(&b.channels));
+        assert_eq!(bits(&a.adaptation), bits(&b.adaptation));
+        assert_eq!(bits(&a.stimulus_adaptation), bits(&b.stimulus_adaptation));
+        assert_eq!(bits(&a.dna_modifiers), bits(&b.dna_modifiers));
+    }
+}